@@ -0,0 +1,59 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use syntect::dumps::dump_to_file;
+use syntect::highlighting::ThemeSet;
+use syntect::parsing::SyntaxSet;
+
+// This dumps syntect's *bundled* syntaxes/themes only. Operator-supplied
+// syntaxes/themes (SYNTECT_SYNTAXES_DIR / SYNTECT_THEMES_DIR) are folded in
+// at server startup instead of here, since they're a runtime deployment
+// concern, not something fixed at compile time — see build_syntax_set() and
+// load_custom_themes() in main.rs.
+fn main() {
+    let out_dir = env::var("OUT_DIR").expect("OUT_DIR not set");
+    let out_dir = Path::new(&out_dir);
+
+    dump_syntax_set(out_dir);
+    dump_theme_set(out_dir);
+}
+
+fn dump_syntax_set(out_dir: &Path) {
+    let syntax_set = SyntaxSet::load_defaults_newlines();
+    dump_to_file(&syntax_set, out_dir.join("syntaxes.bin")).expect("failed to dump syntax set");
+}
+
+// dump_theme_set dumps every bundled theme to its own binary blob under
+// OUT_DIR/themes/, and writes a generated `theme_dumps.rs` mapping theme name
+// -> `include_bytes!` so each theme can be deserialized lazily, on first use,
+// at runtime.
+fn dump_theme_set(out_dir: &Path) {
+    let theme_set = ThemeSet::load_defaults();
+
+    let themes_dir = out_dir.join("themes");
+    fs::create_dir_all(&themes_dir).expect("failed to create themes output dir");
+
+    // Theme dump files are keyed by index rather than a hash of the name, so
+    // two names can never collide and silently clobber each other's dump.
+    let mut entries = Vec::new();
+    for (index, (name, theme)) in theme_set.themes.iter().enumerate() {
+        let file_name = format!("{}.bin", index);
+        let dest = themes_dir.join(&file_name);
+        dump_to_file(theme, &dest).expect("failed to dump theme");
+        entries.push((name.clone(), file_name));
+    }
+
+    let mut generated = String::new();
+    generated.push_str("pub static THEME_DUMPS: &[(&str, &[u8])] = &[\n");
+    for (name, file_name) in &entries {
+        generated.push_str(&format!(
+            "    ({:?}, include_bytes!(concat!(env!(\"OUT_DIR\"), \"/themes/{}\"))),\n",
+            name, file_name
+        ));
+    }
+    generated.push_str("];\n");
+
+    fs::write(out_dir.join("theme_dumps.rs"), generated)
+        .expect("failed to write theme_dumps.rs");
+}
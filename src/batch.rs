@@ -0,0 +1,103 @@
+use std::panic;
+use std::sync::Arc;
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::sync::Semaphore;
+
+use crate::{highlight, Query};
+
+// MAX_CONCURRENT_BATCH_ITEMS bounds how many items of a single /batch request
+// are highlighted at once, so a large batch can use multiple cores without
+// spawning an unbounded number of blocking threads.
+const MAX_CONCURRENT_BATCH_ITEMS: usize = 8;
+
+#[derive(Debug, Deserialize)]
+struct BatchQuery {
+    queries: Vec<Query>,
+}
+
+pub(crate) async fn batch_handler(
+    q: BatchQuery,
+) -> Result<warp::reply::Json, warp::Rejection> {
+    tracing::info!(batch_len = q.queries.len());
+
+    // Acquire a permit before spawning each task (rather than inside it), so
+    // a large batch can't fan out more in-flight tasks than
+    // MAX_CONCURRENT_BATCH_ITEMS up front; the loop itself blocks on
+    // `acquire_owned` once the bound is reached.
+    let semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT_BATCH_ITEMS));
+    let mut tasks = Vec::with_capacity(q.queries.len());
+    for query in q.queries.into_iter() {
+        let permit = semaphore
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("semaphore should not be closed");
+        tasks.push(tokio::spawn(async move {
+            let _permit = permit;
+            tokio::task::spawn_blocking(move || highlight_one(&query))
+                .await
+                .unwrap_or_else(|err| {
+                    tracing::error!(reason = ?err);
+                    json!({"error": "highlighting task failed to complete", "code": "panic"})
+                })
+        }));
+    }
+
+    let mut results: Vec<Value> = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        results.push(task.await.unwrap_or_else(|err| {
+            tracing::error!(reason = ?err);
+            json!({"error": "highlighting task failed to complete", "code": "panic"})
+        }));
+    }
+
+    Ok(warp::reply::json(&results))
+}
+
+// highlight_one mirrors the per-request `catch_unwind` isolation in
+// highlight_handler, so one malformed item in a batch can't take the rest of
+// the batch down with it.
+fn highlight_one(q: &Query) -> Value {
+    match panic::catch_unwind(|| highlight(q)) {
+        Ok(v) => v,
+        Err(err) => {
+            tracing::error!(backtrace = ?err);
+            json!({"error": "panic while highlighting code", "code": "panic"})
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use warp::Reply;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn batch_returns_highlighted_data() {
+        let query: Query = serde_json::from_value(json!({
+            "extension": "rs",
+            "theme": "base16-ocean.dark",
+            "code": "fn main() {}",
+        }))
+        .unwrap();
+
+        let batch = BatchQuery {
+            queries: vec![query],
+        };
+        let reply = batch_handler(batch).await.unwrap();
+        let body = warp::hyper::body::to_bytes(reply.into_response().into_body())
+            .await
+            .unwrap();
+        let results: Vec<Value> = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(results.len(), 1);
+        assert!(
+            results[0].get("data").is_some(),
+            "expected highlighted data, got: {:?}",
+            results[0]
+        );
+    }
+}
@@ -1,31 +1,176 @@
+use std::collections::HashMap;
 use std::env;
+use std::fs;
 use std::panic;
 use std::path::Path;
+use std::sync::{Arc, Mutex};
 
 use lazy_static::lazy_static;
 use serde::Deserialize;
 use serde_json::{json, Value};
 use syntect::{
-    highlighting::ThemeSet,
+    highlighting::{Theme, ThemeSet},
     html::{highlighted_html_for_string, ClassStyle},
     parsing::SyntaxSet,
 };
 use tracing_subscriber::fmt::format::FmtSpan;
 use warp::{reply::Json, Filter, Rejection};
 
-mod css_table;
+mod batch;
+pub(crate) mod css_table;
+mod markdown;
 use css_table::ClassedTableGenerator;
 
-thread_local! {
-    static SYNTAX_SET: SyntaxSet = SyntaxSet::load_defaults_newlines();
-}
+// SYNTECT_SYNTAXES_DIR, when set, points at a directory of `.sublime-syntax`
+// files that are folded into the bundled syntax set once at server startup.
+const SYNTECT_SYNTAXES_DIR: &str = "SYNTECT_SYNTAXES_DIR";
+
+// SYNTECT_THEMES_DIR, when set, points at a directory of `.tmTheme` files
+// that are folded into the bundled themes once at server startup, keyed by
+// file stem.
+const SYNTECT_THEMES_DIR: &str = "SYNTECT_THEMES_DIR";
+
+// The bundled syntax/theme set is baked into the binary at build time (see
+// build.rs) so no parsing happens per-request or per-thread; any
+// operator-supplied SYNTECT_SYNTAXES_DIR/SYNTECT_THEMES_DIR is then folded in
+// once, at startup, below.
+include!(concat!(env!("OUT_DIR"), "/theme_dumps.rs"));
 
 lazy_static! {
-    static ref THEME_SET: ThemeSet = ThemeSet::load_defaults();
+    // A single shared syntax set: the bundled defaults, deserialized once
+    // from the dump produced by build.rs, augmented with SYNTECT_SYNTAXES_DIR
+    // (if set) at startup — instead of re-parsing per thread via
+    // `thread_local!`, or re-scanning the directory on every request.
+    pub(crate) static ref SYNTAX_SET: Arc<SyntaxSet> = Arc::new(build_syntax_set());
+
+    // Themes found in SYNTECT_THEMES_DIR, loaded once at startup.
+    static ref CUSTOM_THEMES: HashMap<String, Arc<Theme>> = load_custom_themes();
+
+    // Bundled themes are dumped individually at build time and deserialized
+    // lazily, the first time each name is requested, then cached here.
+    static ref THEME_CACHE: Mutex<HashMap<String, Arc<Theme>>> = Mutex::new(HashMap::new());
+
+    // Errors encountered while loading user-supplied syntaxes/themes at
+    // startup, surfaced via list_features() so operators can see what (if
+    // anything) failed to load instead of silently falling back to defaults.
+    static ref ASSET_LOAD_ERRORS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+}
+
+fn record_asset_load_error(err: String) {
+    tracing::warn!("{}", err);
+    ASSET_LOAD_ERRORS.lock().unwrap().push(err);
+}
+
+fn build_syntax_set() -> SyntaxSet {
+    let base: SyntaxSet =
+        syntect::dumps::from_binary(include_bytes!(concat!(env!("OUT_DIR"), "/syntaxes.bin")));
+
+    let dir = match env::var(SYNTECT_SYNTAXES_DIR) {
+        Ok(dir) => dir,
+        Err(_) => return base,
+    };
+
+    let mut builder = base.into_builder();
+    if let Err(err) = builder.add_from_folder(&dir, true) {
+        record_asset_load_error(format!(
+            "failed to load syntaxes from {} ({}): {}",
+            SYNTECT_SYNTAXES_DIR, dir, err
+        ));
+    }
+    builder.build()
+}
+
+fn load_custom_themes() -> HashMap<String, Arc<Theme>> {
+    let mut themes = HashMap::new();
+    let dir = match env::var(SYNTECT_THEMES_DIR) {
+        Ok(dir) => dir,
+        Err(_) => return themes,
+    };
+
+    match fs::read_dir(&dir) {
+        Ok(entries) => {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.extension().and_then(|e| e.to_str()) != Some("tmTheme") {
+                    continue;
+                }
+                let name = match path.file_stem().and_then(|s| s.to_str()) {
+                    Some(name) => name.to_owned(),
+                    None => continue,
+                };
+                match ThemeSet::get_theme(&path) {
+                    Ok(theme) => {
+                        themes.insert(name, Arc::new(theme));
+                    }
+                    Err(err) => record_asset_load_error(format!(
+                        "failed to load theme {}: {}",
+                        path.display(),
+                        err
+                    )),
+                }
+            }
+        }
+        Err(err) => record_asset_load_error(format!(
+            "failed to read {} ({}): {}",
+            SYNTECT_THEMES_DIR, dir, err
+        )),
+    }
+    themes
+}
+
+// get_theme looks up a theme by name: first among any operator-supplied
+// custom themes, then the bundled ones, deserializing the latter from its
+// embedded dump on first use and caching the result for subsequent requests.
+pub(crate) fn get_theme(name: &str) -> Option<Arc<Theme>> {
+    if let Some(theme) = CUSTOM_THEMES.get(name) {
+        return Some(theme.clone());
+    }
+    if let Some(theme) = THEME_CACHE.lock().unwrap().get(name) {
+        return Some(theme.clone());
+    }
+    let bytes = THEME_DUMPS.iter().find(|(n, _)| *n == name)?.1;
+    let theme = Arc::new(syntect::dumps::from_binary::<Theme>(bytes));
+    THEME_CACHE
+        .lock()
+        .unwrap()
+        .insert(name.to_owned(), theme.clone());
+    Some(theme)
+}
+
+// SYNTECT_FALLBACK_THEME names the theme used in place of an unrecognized
+// `theme` query field, so a typo'd or unknown theme degrades gracefully
+// instead of always failing the request outright.
+const SYNTECT_FALLBACK_THEME: &str = "SYNTECT_FALLBACK_THEME";
+
+fn fallback_theme_name() -> String {
+    env::var(SYNTECT_FALLBACK_THEME).unwrap_or_else(|_| "InspiredGitHub".to_owned())
+}
+
+// resolve_theme looks up `name`, falling back to the configured fallback
+// theme if it's unknown. Only if even the fallback theme can't be resolved
+// (e.g. a misconfigured SYNTECT_FALLBACK_THEME) does it report an error,
+// which includes every available theme name so callers can self-correct.
+pub(crate) fn resolve_theme(name: &str) -> Result<Arc<Theme>, Value> {
+    if let Some(theme) = get_theme(name) {
+        return Ok(theme);
+    }
+    if let Some(theme) = get_theme(&fallback_theme_name()) {
+        return Ok(theme);
+    }
+    let available: Vec<&str> = THEME_DUMPS
+        .iter()
+        .map(|(name, _)| *name)
+        .chain(CUSTOM_THEMES.keys().map(String::as_str))
+        .collect();
+    Err(json!({
+        "error": "invalid theme",
+        "code": "invalid_theme",
+        "available": available,
+    }))
 }
 
 #[derive(Debug, Deserialize)]
-struct Query {
+pub(crate) struct Query {
     // Deprecated field with a default empty string value, kept for backwards
     // compatability with old clients.
     #[serde(default)]
@@ -46,11 +191,18 @@ struct Query {
     // theme is ignored if css is true
     theme: String,
 
+    // syntax, when set, names the syntax to highlight with directly (e.g.
+    // "Rust" or "rs"), bypassing the filepath/extension/first-line heuristics
+    // below. Unlike those heuristics, an unrecognized syntax is a hard error
+    // rather than a silent fallback to plaintext.
+    #[serde(default)]
+    syntax: Option<String>,
+
     code: String,
 }
 
 async fn highlight_handler(q: Query) -> Result<Json, Rejection> {
-    tracing::info!(extension = %q.extension, filepath = %q.filepath, code_len = q.code.len());
+    tracing::info!(extension = %q.extension, filepath = %q.filepath, syntax = ?q.syntax, code_len = q.code.len());
     // TODO(slimsag): In an ideal world we wouldn't be relying on catch_unwind
     // and instead Syntect would return Result types when failures occur. This
     // will require some non-trivial work upstream:
@@ -68,86 +220,118 @@ async fn highlight_handler(q: Query) -> Result<Json, Rejection> {
     }
 }
 
-fn highlight(q: &Query) -> Value {
-    panic!("in the streets of london");
-    SYNTAX_SET.with(|syntax_set| {
-        // Determine syntax definition by extension.
-        // panic!("cenas");
-        let mut is_plaintext = false;
-        let syntax_def = if q.filepath.is_empty() {
-            // Legacy codepath, kept for backwards-compatability with old clients.
-            match syntax_set.find_syntax_by_extension(&q.extension) {
-                Some(v) => v,
-                None =>
-                // Fall back: Determine syntax definition by first line.
-                {
-                    match syntax_set.find_syntax_by_first_line(&q.code) {
-                        Some(v) => v,
-                        None => return json!({"error": "invalid extension"}),
-                    }
+// resolve_syntax determines which syntax definition to highlight `code`
+// with. If `syntax` is set, it is resolved by name (falling back to
+// extension) and takes priority over every other heuristic; an unrecognized
+// `syntax` is reported as an error rather than silently falling back to
+// plaintext. Otherwise, the filepath/extension/first-line heuristics that
+// predate the `syntax` field apply unchanged.
+pub(crate) fn resolve_syntax<'a>(
+    syntax_set: &'a SyntaxSet,
+    syntax: Option<&str>,
+    filepath: &str,
+    extension: &str,
+    code: &str,
+) -> Result<(&'a syntect::parsing::SyntaxReference, bool), Value> {
+    if let Some(name) = syntax {
+        return syntax_set
+            .find_syntax_by_name(name)
+            .or_else(|| syntax_set.find_syntax_by_extension(name))
+            .map(|v| (v, false))
+            .ok_or_else(|| json!({"error": format!("invalid syntax: {:?}", name), "code": "invalid_syntax"}));
+    }
+
+    let mut is_plaintext = false;
+    let syntax_def = if filepath.is_empty() {
+        // Legacy codepath, kept for backwards-compatability with old clients.
+        match syntax_set.find_syntax_by_extension(extension) {
+            Some(v) => v,
+            None =>
+            // Fall back: Determine syntax definition by first line.
+            {
+                match syntax_set.find_syntax_by_first_line(code) {
+                    Some(v) => v,
+                    None => return Err(json!({"error": "invalid extension"})),
                 }
             }
-        } else {
-            // Split the input path ("foo/myfile.go") into file name
-            // ("myfile.go") and extension ("go").
-            let path = Path::new(&q.filepath);
-            let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
-            let extension = path.extension().and_then(|x| x.to_str()).unwrap_or("");
-
-            // To determine the syntax definition, we must first check using the
-            // filename as some syntaxes match an "extension" that is actually a
-            // whole file name (e.g. "Dockerfile" or "CMakeLists.txt"); see e.g. https://github.com/trishume/syntect/pull/170
-            //
-            // After that, if we do not find any syntax, we can actually check by
-            // extension and lastly via the first line of the code.
-
-            // First try to find a syntax whose "extension" matches our file
-            // name. This is done due to some syntaxes matching an "extension"
-            // that is actually a whole file name (e.g. "Dockerfile" or "CMakeLists.txt")
-            // see https://github.com/trishume/syntect/pull/170
-            syntax_set
-                .find_syntax_by_extension(file_name)
-                .or_else(|| syntax_set.find_syntax_by_extension(extension))
-                .or_else(|| syntax_set.find_syntax_by_first_line(&q.code))
-                .unwrap_or_else(|| {
-                    is_plaintext = true;
-                    syntax_set.find_syntax_plain_text()
-                })
-        };
+        }
+    } else {
+        // Split the input path ("foo/myfile.go") into file name
+        // ("myfile.go") and extension ("go").
+        let path = Path::new(filepath);
+        let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+        let extension = path.extension().and_then(|x| x.to_str()).unwrap_or("");
 
-        if q.css {
-            let output = ClassedTableGenerator::new(
-                syntax_set,
-                syntax_def,
-                &q.code,
-                q.line_length_limit,
-                ClassStyle::SpacedPrefixed { prefix: "hl-" },
-            )
-            .generate();
-
-            json!({
-                "data": output,
-                "plaintext": is_plaintext,
-            })
-        } else {
-            // TODO(slimsag): return the theme's background color (and other info??) to caller?
-            // https://github.com/trishume/syntect/blob/c8b47758a3872d478c7fc740782cd468b2c0a96b/examples/synhtml.rs#L24
-
-            // Determine theme to use.
-            //
-            // TODO(slimsag): We could let the query specify the theme file's actual
-            // bytes? e.g. via `load_from_reader`.
-            let theme = match THEME_SET.themes.get(&q.theme) {
-                Some(v) => v,
-                None => return json!({"error": "invalid theme", "code": "invalid_theme"}),
-            };
-
-            json!({
-                "data": highlighted_html_for_string(&q.code, syntax_set, syntax_def, theme),
-                "plaintext": is_plaintext,
+        // To determine the syntax definition, we must first check using the
+        // filename as some syntaxes match an "extension" that is actually a
+        // whole file name (e.g. "Dockerfile" or "CMakeLists.txt"); see e.g. https://github.com/trishume/syntect/pull/170
+        //
+        // After that, if we do not find any syntax, we can actually check by
+        // extension and lastly via the first line of the code.
+
+        // First try to find a syntax whose "extension" matches our file
+        // name. This is done due to some syntaxes matching an "extension"
+        // that is actually a whole file name (e.g. "Dockerfile" or "CMakeLists.txt")
+        // see https://github.com/trishume/syntect/pull/170
+        syntax_set
+            .find_syntax_by_extension(file_name)
+            .or_else(|| syntax_set.find_syntax_by_extension(extension))
+            .or_else(|| syntax_set.find_syntax_by_first_line(code))
+            .unwrap_or_else(|| {
+                is_plaintext = true;
+                syntax_set.find_syntax_plain_text()
             })
-        }
-    })
+    };
+
+    Ok((syntax_def, is_plaintext))
+}
+
+pub(crate) fn highlight(q: &Query) -> Value {
+    let syntax_set: &SyntaxSet = &SYNTAX_SET;
+    let (syntax_def, is_plaintext) = match resolve_syntax(
+        syntax_set,
+        q.syntax.as_deref(),
+        &q.filepath,
+        &q.extension,
+        &q.code,
+    ) {
+        Ok(v) => v,
+        Err(err) => return err,
+    };
+
+    if q.css {
+        let output = ClassedTableGenerator::new(
+            syntax_set,
+            syntax_def,
+            &q.code,
+            q.line_length_limit,
+            ClassStyle::SpacedPrefixed { prefix: "hl-" },
+        )
+        .generate();
+
+        json!({
+            "data": output,
+            "plaintext": is_plaintext,
+        })
+    } else {
+        // TODO(slimsag): return the theme's background color (and other info??) to caller?
+        // https://github.com/trishume/syntect/blob/c8b47758a3872d478c7fc740782cd468b2c0a96b/examples/synhtml.rs#L24
+
+        // Determine theme to use, deserializing it from its embedded dump on
+        // first use and falling back to SYNTECT_FALLBACK_THEME if unknown.
+        //
+        // TODO(slimsag): We could let the query specify the theme file's actual
+        // bytes? e.g. via `load_from_reader`.
+        let theme = match resolve_theme(&q.theme) {
+            Ok(v) => v,
+            Err(err) => return err,
+        };
+
+        json!({
+            "data": highlighted_html_for_string(&q.code, syntax_set, syntax_def, &theme),
+            "plaintext": is_plaintext,
+        })
+    }
 }
 
 async fn handle_rejection(err: Rejection) -> Result<Json, Rejection> {
@@ -165,20 +349,32 @@ fn list_features() {
     // List embedded themes.
     println!("## Embedded themes:");
     println!();
-    for t in THEME_SET.themes.keys() {
-        println!("- `{}`", t);
+    for (name, _) in THEME_DUMPS.iter() {
+        println!("- `{}`", name);
+    }
+    for name in CUSTOM_THEMES.keys() {
+        println!("- `{}` (from {})", name, SYNTECT_THEMES_DIR);
     }
     println!();
 
     // List supported file extensions.
-    SYNTAX_SET.with(|syntax_set| {
-        println!("## Supported file extensions:");
+    println!("## Supported file extensions:");
+    println!();
+    for sd in SYNTAX_SET.syntaxes() {
+        println!("- {} (`{}`)", sd.name, sd.file_extensions.join("`, `"));
+    }
+    println!();
+
+    // List any errors encountered loading user-supplied syntaxes/themes.
+    let errors = ASSET_LOAD_ERRORS.lock().unwrap();
+    if !errors.is_empty() {
+        println!("## Asset load errors:");
         println!();
-        for sd in syntax_set.syntaxes() {
-            println!("- {} (`{}`)", sd.name, sd.file_extensions.join("`, `"));
+        for err in errors.iter() {
+            println!("- {}", err);
         }
         println!();
-    });
+    }
 }
 
 #[tokio::main]
@@ -204,11 +400,25 @@ async fn main() {
         .and_then(highlight_handler)
         .with(warp::trace::named("highlight"));
 
+    let markdown = warp::path!("markdown")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and_then(markdown::markdown_handler)
+        .with(warp::trace::named("markdown"));
+
+    let batch = warp::path!("batch")
+        .and(warp::post())
+        .and(warp::body::json())
+        .and_then(batch::batch_handler)
+        .with(warp::trace::named("batch"));
+
     let health = warp::path!("health")
         .map(|| "OK")
         .with(warp::trace::named("health"));
 
     let routes = highlight
+        .or(markdown)
+        .or(batch)
         .or(health)
         .recover(handle_rejection)
         .with(warp::trace::request());
@@ -0,0 +1,212 @@
+use std::panic;
+
+use comrak::nodes::{NodeHtmlBlock, NodeValue};
+use comrak::{format_html, parse_document, Arena, ComrakOptions};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use syntect::html::{highlighted_html_for_string, ClassStyle};
+
+use crate::css_table::ClassedTableGenerator;
+use crate::{resolve_syntax, resolve_theme, SYNTAX_SET};
+
+// The placeholder comrak itself substitutes for raw HTML when `unsafe_` is
+// off; reused here to neutralize source HTML before enabling `unsafe_`.
+const RAW_HTML_OMITTED: &[u8] = b"<!-- raw HTML omitted -->";
+
+#[derive(Debug, Deserialize)]
+struct MarkdownQuery {
+    // If css is set, fenced code blocks are rendered as HTML tables with CSS
+    // classes annotating the highlighted types, same as in `Query`.
+    #[serde(default)]
+    css: bool,
+
+    // line_length_limit is ignored if css is false
+    line_length_limit: Option<usize>,
+
+    // theme is ignored if css is true
+    theme: String,
+
+    code: String,
+}
+
+pub(crate) async fn markdown_handler(q: MarkdownQuery) -> Result<warp::reply::Json, warp::Rejection> {
+    tracing::info!(code_len = q.code.len());
+    let result = panic::catch_unwind(|| render_markdown(&q));
+    match result {
+        Err(err) => {
+            tracing::error!(backtrace = ?err);
+            Ok(warp::reply::json(
+                &json!({"error": "panic while rendering markdown", "code": "panic"}),
+            ))
+        }
+        Ok(v) => Ok(warp::reply::json(&v)),
+    }
+}
+
+fn render_markdown(q: &MarkdownQuery) -> Value {
+    let theme = if q.css {
+        None
+    } else {
+        match resolve_theme(&q.theme) {
+            Ok(v) => Some(v),
+            Err(err) => return err,
+        }
+    };
+
+    let arena = Arena::new();
+    let mut options = ComrakOptions::default();
+    let root = parse_document(&arena, &q.code, &options);
+
+    // Neutralize any raw HTML that came from the source document *before* we
+    // flip `unsafe_` on below. Otherwise enabling `unsafe_` to let our own
+    // generated code-block markup through would also let arbitrary
+    // user-supplied `<script>`/`<img onerror=...>` etc. straight through
+    // unescaped — an XSS sink the plain `/` highlight route doesn't have.
+    for node in root.descendants() {
+        let mut data = node.data.borrow_mut();
+        match &mut data.value {
+            NodeValue::HtmlBlock(block) => block.literal = RAW_HTML_OMITTED.to_vec(),
+            NodeValue::HtmlInline(literal) => *literal = RAW_HTML_OMITTED.to_vec(),
+            _ => {}
+        }
+    }
+
+    for node in root.descendants() {
+        let code_block = {
+            let data = node.data.borrow();
+            match &data.value {
+                NodeValue::CodeBlock(block) => Some((
+                    String::from_utf8_lossy(&block.info).into_owned(),
+                    String::from_utf8_lossy(&block.literal).into_owned(),
+                )),
+                _ => None,
+            }
+        };
+        let (info, literal) = match code_block {
+            Some(v) => v,
+            None => continue,
+        };
+
+        // The info string can carry more than the language (e.g. "rust,ignore"
+        // or "go linenos"); only its leading token is the language itself.
+        let lang = info
+            .split_whitespace()
+            .next()
+            .unwrap_or("")
+            .split(',')
+            .next()
+            .unwrap_or("");
+
+        // The language is first looked up as an extension or a syntax name
+        // (case-insensitively, so "rust" finds "Rust"). Failing that, reuse
+        // the same syntax resolution `highlight` uses: treat the fence's info
+        // string as the file's extension by synthesizing a fake path, so an
+        // unrecognized language degrades to plaintext instead of failing the
+        // whole document.
+        let syntax_def = match SYNTAX_SET.find_syntax_by_token(lang) {
+            Some(v) => v,
+            None => {
+                let fake_path = format!("file.{}", lang);
+                match resolve_syntax(&SYNTAX_SET, None, &fake_path, "", &literal) {
+                    Ok((v, _)) => v,
+                    Err(_) => continue,
+                }
+            }
+        };
+
+        let highlighted = if q.css {
+            ClassedTableGenerator::new(
+                &SYNTAX_SET,
+                syntax_def,
+                &literal,
+                q.line_length_limit,
+                ClassStyle::SpacedPrefixed { prefix: "hl-" },
+            )
+            .generate()
+        } else {
+            highlighted_html_for_string(&literal, &SYNTAX_SET, syntax_def, theme.as_ref().unwrap())
+        };
+
+        node.data.borrow_mut().value = NodeValue::HtmlBlock(NodeHtmlBlock {
+            block_type: 0,
+            literal: highlighted.into_bytes(),
+        });
+    }
+
+    // Safe now: the only HtmlBlock/HtmlInline nodes left are either the
+    // placeholders substituted in above or the highlighted code blocks we
+    // just generated ourselves, so it's fine to have format_html emit raw
+    // HTML verbatim.
+    options.render.unsafe_ = true;
+    let mut html = Vec::new();
+    format_html(root, &options, &mut html).expect("formatting markdown failed");
+
+    json!({
+        "data": String::from_utf8_lossy(&html).into_owned(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn highlights_fenced_code_blocks() {
+        let q = MarkdownQuery {
+            css: true,
+            line_length_limit: None,
+            theme: String::new(),
+            code: "```rs\nfn main() {}\n```".to_owned(),
+        };
+
+        let result = render_markdown(&q);
+        let html = result["data"].as_str().expect("data field should be a string");
+        assert!(
+            html.contains("<table"),
+            "expected highlighted code block as a table, got: {}",
+            html
+        );
+    }
+
+    #[test]
+    fn strips_raw_html_from_source() {
+        let q = MarkdownQuery {
+            css: true,
+            line_length_limit: None,
+            theme: String::new(),
+            code: "<script>alert(1)</script>\n\nhi <img onerror=\"alert(1)\"> there".to_owned(),
+        };
+
+        let result = render_markdown(&q);
+        let html = result["data"].as_str().expect("data field should be a string");
+        assert!(
+            !html.contains("<script") && !html.contains("onerror"),
+            "expected raw HTML to be stripped, got: {}",
+            html
+        );
+        assert!(html.contains("<!-- raw HTML omitted -->"));
+    }
+
+    #[test]
+    fn resolves_language_from_info_string_with_extra_tokens() {
+        let q = MarkdownQuery {
+            css: true,
+            line_length_limit: None,
+            theme: String::new(),
+            code: "```rust,ignore\nfn main() {}\n```".to_owned(),
+        };
+
+        let result = render_markdown(&q);
+        let html = result["data"].as_str().expect("data field should be a string");
+        assert!(
+            html.contains("<table"),
+            "expected \"rust,ignore\" info string to still resolve to Rust, got: {}",
+            html
+        );
+        assert!(
+            html.contains("hl-rust"),
+            "expected \"rust,ignore\" info string to still resolve to Rust, got: {}",
+            html
+        );
+    }
+}